@@ -0,0 +1,77 @@
+use std::{env, time::Duration};
+
+/// Runtime configuration, loaded from environment variables with sensible
+/// defaults so the proxy can be deployed without recompiling.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind_addr: String,
+    pub db_path: String,
+    pub workers: usize,
+    pub ttl: u16,
+    pub cache_client_errors: bool,
+    pub cache_server_errors: bool,
+    pub cache_only: bool,
+    pub upstream_user_agent: String,
+    pub upstream_timeout: Duration,
+    pub max_body_size: usize,
+    /// Connection string for the `postgres` `CacheStore` feature. When unset
+    /// (the default), the proxy uses `SqliteStore` against `db_path`.
+    pub database_url: Option<String>,
+    /// Origins allowed to make cross-origin requests. `["*"]` (the default)
+    /// allows any origin; otherwise each matching origin is echoed back.
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
+    pub cors_max_age: usize,
+    pub cors_allow_credentials: bool,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            bind_addr: env_string("BIND_ADDR", "127.0.0.1:8080"),
+            db_path: env_string("CACHE_DB_PATH", "cache.db"),
+            workers: env_parse("WORKERS", default_workers()),
+            ttl: env_parse("CACHE_TTL", 0),
+            cache_client_errors: env_parse("CACHE_CLIENT_ERRORS", true),
+            cache_server_errors: env_parse("CACHE_SERVER_ERRORS", false),
+            cache_only: env_parse("CACHE_ONLY", false),
+            upstream_user_agent: env_string("UPSTREAM_USER_AGENT", "awc-example/1.0"),
+            upstream_timeout: Duration::from_secs(env_parse("UPSTREAM_TIMEOUT_SECS", 30)),
+            max_body_size: env_parse("UPSTREAM_MAX_BODY_BYTES", 10 * 1024 * 1024),
+            database_url: env::var("DATABASE_URL").ok(),
+            cors_allowed_origins: env_list("CORS_ALLOWED_ORIGINS", &["*"]),
+            cors_allowed_methods: env_list("CORS_ALLOWED_METHODS", &["*"]),
+            cors_allowed_headers: env_list("CORS_ALLOWED_HEADERS", &["*"]),
+            cors_max_age: env_parse("CORS_MAX_AGE_SECS", 3600),
+            cors_allow_credentials: env_parse("CORS_ALLOW_CREDENTIALS", false),
+        }
+    }
+}
+
+fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+fn env_string(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default.to_string())
+}
+
+fn env_list(key: &str, default: &[&str]) -> Vec<String> {
+    match env::var(key) {
+        Ok(value) => value.split(',').map(|v| v.trim().to_string()).collect(),
+        Err(_) => default.iter().map(|v| v.to_string()).collect(),
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            log::warn!("Invalid value for {key}, falling back to default");
+            default
+        }),
+        Err(_) => default,
+    }
+}
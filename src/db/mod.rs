@@ -0,0 +1,318 @@
+mod sqlite;
+pub use sqlite::SqliteStore;
+
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+
+use std::{collections::HashMap, time::Duration};
+
+use actix_web::{
+    error,
+    http::{header::HeaderMap, Method, StatusCode},
+    Error, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use async_trait::async_trait;
+use awc::error::{PayloadError, SendRequestError};
+use chrono::{DateTime, Utc};
+use url::Url;
+
+#[derive(Debug)]
+pub struct Entry {
+    pub method: Method,
+    pub url: Url,
+    pub content: Vec<u8>,
+    pub headers: HttpHeaders,
+    pub status_code: StatusCode,
+    pub last_update: DateTime<Utc>,
+    /// When the entry stops being servable without revalidation, derived from
+    /// the upstream `Cache-Control`/`Expires` headers (or `CacheSettings::ttl`
+    /// when the origin gave no freshness directive). `None` means "cache
+    /// forever": the entry is never treated as stale or revalidated.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Entry {
+    /// Whether this entry can be served as-is, without asking upstream.
+    fn is_fresh(&self) -> bool {
+        match self.expires {
+            Some(expires) => Utc::now() < expires,
+            None => true,
+        }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.0.get(name)?.first().map(String::as_str)
+    }
+}
+
+impl Into<HttpResponse> for &Entry {
+    fn into(self) -> HttpResponse {
+        let mut builder = HttpResponseBuilder::new(self.status_code);
+        for (key, values) in &self.headers.0 {
+            for value in values {
+                builder.append_header((key.to_owned(), value.to_owned()));
+            }
+        }
+        builder.body(self.content.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub client_errors: bool,
+    pub server_errors: bool,
+    pub ttl: u16,
+    pub cache_only: bool,
+    pub upstream_timeout: Duration,
+    pub max_body_size: usize,
+}
+
+impl CacheSettings {
+    pub fn new(
+        client_errors: bool,
+        server_errors: bool,
+        ttl: u16,
+        cache_only: bool,
+        upstream_timeout: Duration,
+        max_body_size: usize,
+    ) -> Self {
+        CacheSettings {
+            client_errors,
+            server_errors,
+            ttl,
+            cache_only,
+            upstream_timeout,
+            max_body_size,
+        }
+    }
+}
+
+/// Headers as stored alongside a cached entry, independent of which backend
+/// persists them.
+#[derive(Debug)]
+pub struct HttpHeaders(pub(crate) HashMap<String, Vec<String>>);
+
+impl From<&HeaderMap> for HttpHeaders {
+    fn from(headers: &HeaderMap) -> Self {
+        let mut m: HashMap<String, Vec<String>> = HashMap::new();
+        for k in headers.keys() {
+            m.insert(
+                k.to_string(),
+                headers
+                    .get_all(k)
+                    .map(|x| x.to_str().unwrap().into())
+                    .collect(),
+            );
+        }
+        Self(m)
+    }
+}
+
+/// Storage backend for cached entries. SQLite (`SqliteStore`) is the default;
+/// `PostgresStore` is available behind the `postgres` feature so the proxy
+/// can be shared across instances against a central database instead of a
+/// single local file.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    async fn get(
+        &self,
+        method: &Method,
+        url: &Url,
+        settings: &CacheSettings,
+    ) -> Result<Option<Entry>, Error>;
+
+    async fn upsert(&self, entry: &Entry) -> Result<(), Error>;
+}
+
+/// Derive a per-entry expiry from the upstream response's `Cache-Control:
+/// max-age` or `Expires` header, falling back to `default_ttl` seconds when
+/// the origin gave no freshness directive. `None` means cache forever.
+fn compute_expiry(headers: &HeaderMap, now: DateTime<Utc>, default_ttl: u16) -> Option<DateTime<Utc>> {
+    if let Some(max_age) = header_str(headers, "cache-control").and_then(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .find_map(|directive| directive.strip_prefix("max-age="))
+            .and_then(|seconds| seconds.parse::<i64>().ok())
+    }) {
+        // Upstream sends this value; clamp it so a huge max-age can't
+        // overflow `Duration::seconds` or the later `DateTime` addition.
+        let max_age = max_age.clamp(0, MAX_EXPIRY_SECS);
+        if let Some(expires) = now.checked_add_signed(chrono::Duration::seconds(max_age)) {
+            return Some(expires);
+        }
+    }
+    if let Some(expires) = header_str(headers, "expires") {
+        if let Ok(parsed) = DateTime::parse_from_rfc2822(expires) {
+            return Some(parsed.with_timezone(&Utc));
+        }
+    }
+    if default_ttl > 0 {
+        return now.checked_add_signed(chrono::Duration::seconds(default_ttl.into()));
+    }
+    None
+}
+
+/// Upper bound for a derived expiry offset (roughly 100 years), so a
+/// malicious or malformed `max-age` can't overflow `chrono::Duration` or the
+/// `DateTime` addition it feeds into.
+const MAX_EXPIRY_SECS: i64 = 100 * 365 * 24 * 60 * 60;
+
+fn is_no_store(headers: &HeaderMap) -> bool {
+    header_str(headers, "cache-control")
+        .map(|value| value.split(',').any(|d| d.trim().eq_ignore_ascii_case("no-store")))
+        .unwrap_or(false)
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// Result of a round-trip to the upstream origin.
+enum Upstream {
+    /// Origin confirmed a conditionally-requested entry is still valid; only
+    /// its `expires` needs refreshing, not its content.
+    NotModified { expires: Option<DateTime<Utc>> },
+    /// A full response, plus whether it is cacheable (i.e. not `no-store`).
+    Entry { entry: Entry, cacheable: bool },
+}
+
+/// Send the request upstream. When `revalidating` is set, the entry's
+/// `ETag`/`Last-Modified` are attached as conditional request headers so the
+/// origin can reply with `304 Not Modified`.
+async fn fetch_upstream(
+    settings: &CacheSettings,
+    request: &HttpRequest,
+    url: &Url,
+    client: &awc::Client,
+    revalidating: Option<&Entry>,
+) -> Result<Upstream, Error> {
+    let mut client_req = client
+        .request(request.method().to_owned(), url.to_string())
+        .timeout(settings.upstream_timeout);
+    for header in request.headers() {
+        client_req = client_req.insert_header(header);
+    }
+    client_req = client_req.insert_header(("host", url.host().unwrap().to_string()));
+    if let Some(entry) = revalidating {
+        if let Some(etag) = entry.header("etag") {
+            client_req = client_req.insert_header(("if-none-match", etag.to_owned()));
+        }
+        if let Some(last_modified) = entry.header("last-modified") {
+            client_req = client_req.insert_header(("if-modified-since", last_modified.to_owned()));
+        }
+    }
+    log::debug!("{} {}", client_req.get_method(), client_req.get_uri());
+    let mut res = match client_req.send().await {
+        Ok(res) => res,
+        Err(SendRequestError::Timeout) => {
+            log::warn!("Upstream request to {url} timed out");
+            return Err(error::ErrorGatewayTimeout("upstream request timed out"));
+        }
+        Err(err) => return Err(error::ErrorBadGateway(err)),
+    };
+
+    if revalidating.is_some() && res.status() == StatusCode::NOT_MODIFIED {
+        log::info!("Upstream confirmed cached entry is still fresh (304)");
+        return Ok(Upstream::NotModified {
+            expires: compute_expiry(res.headers(), Utc::now(), settings.ttl),
+        });
+    }
+
+    let content = match res.body().limit(settings.max_body_size).await {
+        Ok(content) => content,
+        Err(PayloadError::Overflow) => {
+            log::warn!("Upstream response from {url} exceeded the body size limit");
+            return Err(error::ErrorPayloadTooLarge("upstream response too large"));
+        }
+        Err(err) => return Err(error::ErrorBadGateway(err)),
+    };
+    log::debug!("Response: {:?}", res); // <- server http response
+    let mut client_response = HttpResponse::build(res.status());
+    for (header_name, header_value) in res
+        .headers()
+        .iter()
+        .filter(|(h, _)| !(*h == "connection" || *h == "content-encoding"))
+    {
+        // TODO factor out header filtering
+        client_response.insert_header((header_name.clone(), header_value.clone()));
+    }
+
+    let client_response = client_response.finish();
+    let cacheable = !is_no_store(client_response.headers());
+    let entry = Entry {
+        method: request.method().into(),
+        url: url.clone(),
+        content: content.to_vec(), // response.body(),
+        expires: compute_expiry(client_response.headers(), Utc::now(), settings.ttl),
+        headers: HttpHeaders::from(client_response.headers()),
+        status_code: client_response.status(),
+        last_update: Utc::now(),
+    };
+    Ok(Upstream::Entry { entry, cacheable })
+}
+
+pub async fn execute(
+    store: &dyn CacheStore,
+    settings: &CacheSettings,
+    request: &HttpRequest,
+    url: &Url,
+    client: &awc::Client,
+) -> Result<HttpResponse, Error> {
+    log::debug!("{:?}", request.uri());
+    let cached = store.get(request.method(), url, settings).await?;
+
+    let entry = match cached {
+        Some(entry) if entry.is_fresh() => {
+            log::info!("Serving fresh entry from cache");
+            entry
+        }
+        Some(entry) if settings.cache_only => {
+            log::info!("Cached entry is stale, but cache_only is set, serving it as-is");
+            entry
+        }
+        Some(entry) => {
+            log::info!("Cached entry is stale, revalidating with upstream");
+            match fetch_upstream(settings, request, url, client, Some(&entry)).await? {
+                Upstream::NotModified { expires } => {
+                    let entry = Entry {
+                        expires,
+                        last_update: Utc::now(),
+                        ..entry
+                    };
+                    store.upsert(&entry).await?;
+                    entry
+                }
+                Upstream::Entry { entry, cacheable } => {
+                    if cacheable {
+                        store.upsert(&entry).await?;
+                    } else {
+                        log::debug!("Not caching response marked no-store");
+                    }
+                    entry
+                }
+            }
+        }
+        None if settings.cache_only => {
+            log::info!("No match, cache_only is set, refusing to contact upstream");
+            return Ok(HttpResponse::build(StatusCode::GATEWAY_TIMEOUT).finish());
+        }
+        None => {
+            log::info!("No match, proxying");
+            match fetch_upstream(settings, request, url, client, None).await? {
+                Upstream::NotModified { .. } => unreachable!("no conditional headers were sent"),
+                Upstream::Entry { entry, cacheable } => {
+                    if cacheable {
+                        store.upsert(&entry).await?;
+                    } else {
+                        log::debug!("Not caching response marked no-store");
+                    }
+                    entry
+                }
+            }
+        }
+    };
+    Ok((&entry).into())
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+
+use actix_web::{error, http::Method, Error};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Config as PoolConfig, CreatePoolError, Pool, PoolError, Runtime};
+use tokio_postgres::NoTls;
+use url::Url;
+
+use super::{CacheSettings, CacheStore, Entry, HttpHeaders};
+
+/// Everything that can go wrong building a `PostgresStore` or decoding a row
+/// out of it, so callers get a real error instead of a panicking worker.
+#[derive(Debug)]
+pub enum PostgresStoreError {
+    Pool(PoolError),
+    CreatePool(CreatePoolError),
+    Db(tokio_postgres::Error),
+    /// A stored row contained data that isn't a valid `Entry` (corrupt
+    /// `method`/`url`/`status_code`, or `headers` that don't decode as JSON).
+    CorruptRow(String),
+}
+
+impl fmt::Display for PostgresStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostgresStoreError::Pool(err) => write!(f, "Postgres pool error: {err}"),
+            PostgresStoreError::CreatePool(err) => write!(f, "Postgres pool configuration error: {err}"),
+            PostgresStoreError::Db(err) => write!(f, "Postgres error: {err}"),
+            PostgresStoreError::CorruptRow(msg) => write!(f, "corrupt cache row: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for PostgresStoreError {}
+
+impl From<PoolError> for PostgresStoreError {
+    fn from(err: PoolError) -> Self {
+        PostgresStoreError::Pool(err)
+    }
+}
+
+impl From<CreatePoolError> for PostgresStoreError {
+    fn from(err: CreatePoolError) -> Self {
+        PostgresStoreError::CreatePool(err)
+    }
+}
+
+impl From<tokio_postgres::Error> for PostgresStoreError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        PostgresStoreError::Db(err)
+    }
+}
+
+const CREATE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS cache (
+ method TEXT NOT NULL,
+ url TEXT NOT NULL,
+ content BYTEA NOT NULL,
+ headers JSONB NOT NULL,
+ status_code INTEGER NOT NULL,
+ last_update TIMESTAMPTZ NOT NULL DEFAULT now(),
+ expires TIMESTAMPTZ,
+ PRIMARY KEY (method, url)
+)";
+
+const UPSERT_SQL: &str = "
+INSERT INTO cache (method, url, content, headers, status_code, expires) VALUES ($1, $2, $3, $4, $5, $6)
+ ON CONFLICT (method, url) DO UPDATE SET
+ content = excluded.content,
+ headers = excluded.headers,
+ status_code = excluded.status_code,
+ expires = excluded.expires,
+ last_update = now()";
+
+/// `CacheStore` backed by Postgres, so the cache can be shared by multiple
+/// proxy instances against one central database instead of a local file.
+/// Selected at runtime when `DATABASE_URL` is set and the `postgres` Cargo
+/// feature is enabled; `SqliteStore` remains the default otherwise.
+pub struct PostgresStore {
+    pool: Pool,
+}
+
+impl PostgresStore {
+    pub async fn connect(database_url: &str) -> Result<Self, PostgresStoreError> {
+        let mut config = PoolConfig::new();
+        config.url = Some(database_url.to_string());
+        let pool = config.create_pool(Some(Runtime::Tokio1), NoTls)?;
+        pool.get().await?.batch_execute(CREATE_SQL).await?;
+        Ok(PostgresStore { pool })
+    }
+}
+
+#[async_trait]
+impl CacheStore for PostgresStore {
+    async fn get(
+        &self,
+        method: &Method,
+        url: &Url,
+        settings: &CacheSettings,
+    ) -> Result<Option<Entry>, Error> {
+        let conn = self.pool.get().await.map_err(error::ErrorInternalServerError)?;
+        let mut sql = String::from(
+            "SELECT method, url, content, headers, status_code, last_update, expires \
+             FROM cache WHERE method = $1 AND url = $2 AND (status_code < 400",
+        );
+        if settings.client_errors {
+            sql += " OR status_code BETWEEN 400 AND 499";
+        }
+        if settings.server_errors {
+            sql += " OR status_code BETWEEN 500 AND 599";
+        }
+        sql += ")";
+        let row = conn
+            .query_opt(sql.as_str(), &[&method.as_str(), &url.as_str()])
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        row.map(|row| Entry::try_from(&row))
+            .transpose()
+            .map_err(error::ErrorInternalServerError)
+    }
+
+    async fn upsert(&self, entry: &Entry) -> Result<(), Error> {
+        let conn = self.pool.get().await.map_err(error::ErrorInternalServerError)?;
+        let headers = serde_json::to_value(&entry.headers.0).map_err(error::ErrorInternalServerError)?;
+        conn.execute(
+            UPSERT_SQL,
+            &[
+                &entry.method.as_str(),
+                &entry.url.as_str(),
+                &entry.content,
+                &headers,
+                &i32::from(entry.status_code.as_u16()),
+                &entry.expires,
+            ],
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+        Ok(())
+    }
+}
+
+impl TryFrom<&tokio_postgres::Row> for Entry {
+    type Error = PostgresStoreError;
+
+    fn try_from(row: &tokio_postgres::Row) -> Result<Self, Self::Error> {
+        let method: String = row.try_get("method")?;
+        let url: String = row.try_get("url")?;
+        let status_code: i32 = row.try_get("status_code")?;
+        let headers: serde_json::Value = row.try_get("headers")?;
+        Ok(Entry {
+            method: method
+                .parse()
+                .map_err(|err| PostgresStoreError::CorruptRow(format!("invalid method {method:?}: {err}")))?,
+            url: url
+                .parse()
+                .map_err(|err| PostgresStoreError::CorruptRow(format!("invalid url {url:?}: {err}")))?,
+            content: row.try_get("content")?,
+            headers: HttpHeaders(serde_json::from_value(headers).map_err(|err| {
+                PostgresStoreError::CorruptRow(format!("invalid headers: {err}"))
+            })?),
+            status_code: u16::try_from(status_code)
+                .ok()
+                .and_then(|code| actix_web::http::StatusCode::from_u16(code).ok())
+                .ok_or_else(|| PostgresStoreError::CorruptRow(format!("invalid status code {status_code}")))?,
+            last_update: row.try_get::<_, DateTime<Utc>>("last_update")?,
+            expires: row.try_get("expires")?,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+use std::str::FromStr;
+
+use actix_web::{
+    error,
+    http::{Method, StatusCode},
+    Error,
+};
+use async_trait::async_trait;
+use r2d2_sqlite::{rusqlite::named_params, SqliteConnectionManager};
+use rusqlite::{types::FromSql, OpenFlags, Row, ToSql};
+use tokio::sync::Semaphore;
+use url::Url;
+
+use super::{CacheSettings, CacheStore, Entry, HttpHeaders};
+
+pub type Pool = r2d2::Pool<SqliteConnectionManager>;
+
+const CREATE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS cache (
+ method TEXT,
+ url TEXT,
+ content BLOB,
+ headers TEXT,
+ status_code INTEGER,
+ last_update TEXT DEFAULT CURRENT_TIMESTAMP NOT NULL,
+ expires TEXT,
+ PRIMARY KEY (method, url)
+)";
+
+const UPSERT_SQL: &str = "
+INSERT INTO cache (method, url, content, headers, status_code, expires) VALUES (:method, :url, :content, :headers, :status_code, :expires)
+ ON CONFLICT(method, url) DO UPDATE SET
+ content=excluded.content,
+ headers=excluded.headers,
+ status_code=excluded.status_code,
+ expires=excluded.expires,
+ last_update=CURRENT_TIMESTAMP";
+
+impl TryFrom<&Row<'_>> for Entry {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        let m: String = row.get("method")?;
+        Ok(Entry {
+            method: Method::from_str(m.as_str()).unwrap(),
+            url: row.get("url")?,
+            content: row.get("content")?,
+            headers: row.get("headers")?,
+            status_code: StatusCode::from_u16(row.get("status_code")?).unwrap(),
+            last_update: row.get("last_update")?,
+            expires: row.get("expires")?,
+        })
+    }
+}
+
+impl FromSql for HttpHeaders {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        value.as_str().and_then(|s| match serde_json::from_str(s) {
+            Ok(x) => Ok(Self(x)),
+            Err(err) => Err(rusqlite::types::FromSqlError::Other(Box::new(err))),
+        })
+    }
+}
+
+impl ToSql for HttpHeaders {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        match serde_json::to_string(&self.0) {
+            Ok(x) => Ok(rusqlite::types::ToSqlOutput::Owned(
+                rusqlite::types::Value::Text(x),
+            )),
+            Err(err) => Err(rusqlite::Error::ToSqlConversionFailure(Box::new(err))),
+        }
+    }
+}
+
+/// Default `CacheStore`, backed by a single SQLite file shared by every HTTP
+/// worker in this process.
+pub struct SqliteStore {
+    pool: Pool,
+    // Bounds concurrent SQLite access independently of the HTTP worker count.
+    db_semaphore: Semaphore,
+}
+
+impl SqliteStore {
+    pub fn new(db_path: &str, pool_size: u32) -> Result<Self, rusqlite::Error> {
+        // Shared cache + WAL let many pooled connections read/write the same
+        // file concurrently.
+        let manager = SqliteConnectionManager::file(format!("file:{db_path}?cache=shared"))
+            .with_flags(
+                OpenFlags::SQLITE_OPEN_READ_WRITE
+                    | OpenFlags::SQLITE_OPEN_CREATE
+                    | OpenFlags::SQLITE_OPEN_URI,
+            )
+            .with_init(|conn| conn.execute_batch("PRAGMA journal_mode=WAL;"));
+        let pool = r2d2::Pool::builder()
+            .max_size(pool_size)
+            .build(manager)
+            .unwrap();
+        log::debug!("Creating database");
+        pool.get().unwrap().execute(CREATE_SQL, ())?;
+        Ok(SqliteStore {
+            pool,
+            // Sized to match the pool: the semaphore exists to queue requests
+            // asynchronously instead of letting `pool.get()` block an async
+            // worker thread, not to impose a second, smaller limit than the
+            // pool already does.
+            db_semaphore: Semaphore::new(pool_size as usize),
+        })
+    }
+
+    fn select_sql(settings: &CacheSettings) -> String {
+        // Freshness is no longer a fixed age cutoff: it is tracked per-row in
+        // `expires` and checked by the caller, so a stale hit is still
+        // returned here for revalidation instead of being filtered out.
+        let mut sql = String::from("SELECT * FROM cache WHERE method = :method AND url = :url");
+        sql += " AND (status_code < 400";
+        if settings.client_errors {
+            sql += " OR status_code BETWEEN 400 AND 499";
+        }
+        if settings.server_errors {
+            sql += " OR status_code BETWEEN 500 AND 599";
+        }
+        sql += ")";
+        sql
+    }
+}
+
+#[async_trait]
+impl CacheStore for SqliteStore {
+    async fn get(
+        &self,
+        method: &Method,
+        url: &Url,
+        settings: &CacheSettings,
+    ) -> Result<Option<Entry>, Error> {
+        let _permit = self
+            .db_semaphore
+            .acquire()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        let conn = self.pool.get().map_err(error::ErrorInternalServerError)?;
+        let sql = Self::select_sql(settings);
+        let mut stmt = conn.prepare_cached(sql.as_str()).unwrap();
+        let mut entry_iter = stmt
+            .query_map(
+                named_params! {":method": method.to_string(), ":url": url.to_string()},
+                |row| Entry::try_from(row),
+            )
+            .map_err(error::ErrorInternalServerError)?;
+        entry_iter
+            .next()
+            .transpose()
+            .map_err(error::ErrorInternalServerError)
+    }
+
+    async fn upsert(&self, entry: &Entry) -> Result<(), Error> {
+        log::debug!("Saving to database");
+        let _permit = self
+            .db_semaphore
+            .acquire()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        let conn = self.pool.get().map_err(error::ErrorInternalServerError)?;
+        let mut stmt = conn.prepare_cached(UPSERT_SQL).unwrap();
+        stmt.execute(named_params! {
+                ":method": &entry.method.to_string(),
+                ":url": &entry.url,
+                ":content": &entry.content,
+                ":headers": &entry.headers,
+                ":status_code": &entry.status_code.as_str(),
+                ":expires": &entry.expires,
+        })
+        .unwrap();
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use actix_cors::Cors;
 use actix_web::{
     dev::Payload,
     http::StatusCode,
@@ -8,12 +9,19 @@ use actix_web::{
     App, Error as AWError, FromRequest, HttpRequest, HttpResponse, HttpServer, ResponseError,
 };
 use futures_util::future::{err, ok, Ready};
-use r2d2_sqlite::{self, SqliteConnectionManager};
 
+mod config;
 mod db;
-use db::Pool;
+use config::Config;
+use db::CacheStore;
 use serde::{Deserialize, Serialize};
 
+/// Shared state handed to every request handler.
+struct AppState {
+    settings: db::CacheSettings,
+    store: Arc<dyn CacheStore>,
+}
+
 static PATH_RE: &lazy_regex::Lazy<lazy_regex::Regex> =
     lazy_regex::regex!(r"^/?([a-z][a-z0-9+\-.]*:)/+");
 
@@ -93,21 +101,12 @@ impl TryFrom<&str> for ShakyUrl {
 }
 
 async fn cache(
-    data: web::Data<(db::CacheSettings, Pool)>,
+    data: web::Data<AppState>,
     client: web::Data<awc::Client>,
     url: ShakyUrl,
     req: HttpRequest,
 ) -> Result<HttpResponse, AWError> {
-    if req.method() == &actix_web::http::Method::OPTIONS {
-        log::info!("Ignoring {} request", req.method());
-        let mut res = HttpResponse::Ok();
-        res.append_header(("access-control-allow-origin", "*"));
-        res.append_header(("access-control-allow-headers", "*"));
-        return Ok(res.finish());
-    }
-    let settings = &data.0;
-    let db = &data.1;
-    let result = db::execute(&settings, &db, &req, &url.0, &client).await?;
+    let result = db::execute(data.store.as_ref(), &data.settings, &req, &url.0, &client).await?;
     log::debug!("{result:?}");
     log::debug!("{:?}", req.match_info());
     log::debug!("ShakyUrl: {:?}", url);
@@ -126,41 +125,103 @@ enum ListOrString {
 async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("debug"));
 
-    // connect to SQLite DB
-    let manager = SqliteConnectionManager::file("cache.db"); // TODO
-    let pool = Pool::new(manager).unwrap();
-    db::create_db(&pool).unwrap();
-    let settings = db::CacheSettings {
-        client_errors: true,
-        server_errors: false,
-        ttl: 0,
-    };
-    log::info!("starting HTTP proxy server at http://localhost:8080/proxy/");
+    let config = Config::from_env();
+
+    let cpus = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4);
+    let store: Arc<dyn CacheStore> = build_store(&config, cpus as u32).await;
+    let settings = db::CacheSettings::new(
+        config.cache_client_errors,
+        config.cache_server_errors,
+        config.ttl,
+        config.cache_only,
+        config.upstream_timeout,
+        config.max_body_size,
+    );
+    log::info!(
+        "starting HTTP proxy server at http://{}/proxy/",
+        config.bind_addr
+    );
     let client_tls_config = Arc::new(rustls_config());
+    let user_agent = config.upstream_user_agent.clone();
+    let cors_config = config.clone();
     // start HTTP server
     HttpServer::new(move || {
         let client = awc::Client::builder()
             // Wikipedia requires a User-Agent header to make requests
-            .disable_timeout()
-            .add_default_header(("user-agent", "awc-example/1.0"))
+            .add_default_header(("user-agent", user_agent.clone()))
             // a "connector" wraps the stream into an encrypted connection
             .connector(awc::Connector::new().rustls(Arc::clone(&client_tls_config)))
             .finish();
         App::new()
-            // store db pool as Data object
-            .app_data(web::Data::new((settings.clone(), pool.clone())))
+            .app_data(web::Data::new(AppState {
+                settings: settings.clone(),
+                store: Arc::clone(&store),
+            }))
             .app_data(web::Data::new(client))
+            .wrap(build_cors(&cors_config))
             .wrap(middleware::Logger::default())
             .service(web::resource("/proxy/{url_no_query:https?:/.*}").route(web::to(cache)))
             .default_service(web::to(not_found))
     })
-    .bind(("127.0.0.1", 8080))? // TODO
-    .worker_max_blocking_threads(1) // TODO
-    .workers(1) // TODO
+    .bind(&config.bind_addr)?
+    .workers(config.workers)
     .run()
     .await
 }
 
+/// Build the CORS layer from config: an origin allow-list that is reflected
+/// back per request (instead of a blanket wildcard), with configurable
+/// methods/headers/max-age, applied to every response rather than just
+/// preflight.
+fn build_cors(config: &Config) -> Cors {
+    let mut cors = Cors::default();
+    cors = if config.cors_allowed_origins.iter().any(|o| o == "*") {
+        cors.allow_any_origin()
+    } else {
+        config
+            .cors_allowed_origins
+            .iter()
+            .fold(cors, |cors, origin| cors.allowed_origin(origin))
+    };
+    cors = if config.cors_allowed_methods.iter().any(|m| m == "*") {
+        cors.allow_any_method()
+    } else {
+        cors.allowed_methods(config.cors_allowed_methods.iter().map(String::as_str))
+    };
+    cors = if config.cors_allowed_headers.iter().any(|h| h == "*") {
+        cors.allow_any_header()
+    } else {
+        cors.allowed_headers(config.cors_allowed_headers.iter().map(String::as_str))
+    };
+    if config.cors_allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors.max_age(config.cors_max_age)
+}
+
+/// Pick the cache backend: `PostgresStore` when the `postgres` feature is
+/// compiled in and `DATABASE_URL` is set, `SqliteStore` otherwise.
+///
+/// Failing to reach the configured backend is a startup-time configuration
+/// problem, not a per-request one, so it is still fatal here -- but it is
+/// reported with its real cause instead of panicking on a bare `Option`.
+async fn build_store(config: &Config, sqlite_pool_size: u32) -> Arc<dyn CacheStore> {
+    #[cfg(feature = "postgres")]
+    if let Some(database_url) = &config.database_url {
+        return Arc::new(
+            db::PostgresStore::connect(database_url)
+                .await
+                .unwrap_or_else(|err| panic!("failed to connect to Postgres: {err}")),
+        );
+    }
+    Arc::new(
+        db::SqliteStore::new(&config.db_path, sqlite_pool_size)
+            .unwrap_or_else(|err| panic!("failed to open cache database {}: {err}", config.db_path)),
+    )
+}
+
 async fn not_found() -> Result<HttpResponse, AWError> {
     Ok(HttpResponse::build(StatusCode::NOT_FOUND)
         .content_type("application/json")